@@ -8,6 +8,7 @@ use axum::{Router, routing::get};
 use clap::Parser;
 use tokio::net::TcpListener;
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 use tracing::level_filters::LevelFilter;
@@ -15,7 +16,10 @@ use tracing_subscriber::EnvFilter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod util;
-use util::handler::{delete_handler, get_handler, put_handler};
+#[cfg(test)]
+mod tests;
+
+use util::handler::{delete_handler, get_handler, head_handler, put_handler};
 use util::{AppState, Args};
 
 const DEFAULT_LOG_LEVEL: LevelFilter = if cfg!(debug_assertions) {
@@ -24,6 +28,26 @@ const DEFAULT_LOG_LEVEL: LevelFilter = if cfg!(debug_assertions) {
     LevelFilter::INFO
 };
 
+/// Builds the axum app: routes plus the shared middleware stack. Split out from `main`
+/// so tests can spin up the exact same router against a temporary storage directory.
+pub fn create_app_router(app_state: Arc<AppState>) -> Router {
+    let limit_size = app_state.args.limit_size;
+
+    Router::new()
+        .route("/", get("hello, ypb!"))
+        .route("/", put(put_handler))
+        .route("/{*hash}", get(get_handler).head(head_handler))
+        .route("/{*hash}", delete(delete_handler))
+        .layer((
+            TraceLayer::new_for_http(),
+            // Graceful shutdown will wait for outstanding requests to complete. Add a timeout so
+            // requests don't hang forever.
+            TimeoutLayer::new(Duration::from_secs(10)),
+            DefaultBodyLimit::max(limit_size),
+        ))
+        .with_state(app_state)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Enable tracing.
@@ -42,30 +66,38 @@ async fn main() -> Result<()> {
         std::fs::create_dir_all(&args.file_path).context("Failed to create storage directory")?;
     }
 
-    let app_state = Arc::new(AppState { args: args.clone() });
-    // Create a regular axum app.
-    let app = Router::new()
-        .route("/", get("hello, ypb!"))
-        .route("/", put(put_handler))
-        .route("/{*hash}", get(get_handler))
-        .route("/{*hash}", delete(delete_handler))
-        .layer((
-            TraceLayer::new_for_http(),
-            // Graceful shutdown will wait for outstanding requests to complete. Add a timeout so
-            // requests don't hang forever.
-            TimeoutLayer::new(Duration::from_secs(10)),
-            DefaultBodyLimit::max(args.limit_size),
-        ))
-        .with_state(app_state);
+    let (cleaner_tx, cleaner_rx) = tokio::sync::mpsc::unbounded_channel();
+    let staging = util::tmpfile::StagingDir::init(&args.file_path)
+        .await
+        .context("Failed to init staging directory")?;
+    let shutdown = CancellationToken::new();
+
+    let app_state = Arc::new(AppState {
+        args: args.clone(),
+        cleaner_tx,
+        staging: staging.clone(),
+        view_locks: util::meta::new_view_locks(),
+        shutdown: shutdown.clone(),
+    });
+    let app = create_app_router(app_state);
 
     // Create a `TcpListener` using tokio.
     let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port))
         .await
         .with_context(|| format!("Failed to listen on port {}", args.port))?;
 
-    tokio::spawn(util::cleaner::cleaner_task(
+    let reconciler = tokio::spawn(util::cleaner::reconcile_task(
+        args.file_path.clone(),
+        args.cleaner_concurrency,
+        args.reconcile_period_secs,
+        shutdown.clone(),
+    ));
+    let cleaner = tokio::spawn(util::cleaner::cleaner_task(
         args.file_path,
         args.clean_period,
+        cleaner_rx,
+        args.cleaner_concurrency,
+        shutdown.clone(),
     ));
     // Run the server with graceful shutdown
     axum::serve(listener, app)
@@ -73,6 +105,13 @@ async fn main() -> Result<()> {
         .await
         .unwrap();
 
+    // Let in-flight cleanup work finish before tearing down storage.
+    shutdown.cancel();
+    let _ = cleaner.await;
+    let _ = reconciler.await;
+
+    staging.cleanup().await;
+
     Ok(())
 }
 
@@ -9,21 +9,49 @@ use tokio::time;
 
 use crate::{
     create_app_router,
-    util::{AppState, Args},
+    util::{self, AppState, Args, tmpfile::StagingDir},
 };
 
-// Helper function to start a test server
-async fn start_test_server(temp_dir: &TempDir) -> (SocketAddr, Arc<AppState>) {
-    // Create test Args with a small file size limit for testing
-    let args = Args {
+// Default test Args, with a small file size limit for testing
+fn default_test_args(temp_dir: &TempDir) -> Args {
+    Args {
         port: 0, // Use port 0 to let the OS assign a free port
         file_path: temp_dir.path().to_string_lossy().into_owned(),
         clean_period: 3600,
         limit_size: 1024 * 10, // 10KB limit for testing
         syntax_theme: "vs".to_string(),
-    };
+        compression_min_size: 1024,
+        write_compressed_sidecars: true,
+        auth_token: vec![],
+        max_expire_secs: 7 * 24 * 3600,
+        cleaner_concurrency: 10,
+        reconcile_period_secs: 6 * 3600,
+    }
+}
+
+// Helper function to start a test server with the default Args
+async fn start_test_server(temp_dir: &TempDir) -> (SocketAddr, Arc<AppState>) {
+    start_test_server_with_args(default_test_args(temp_dir), false).await
+}
 
-    let app_state = Arc::new(AppState { args: args.clone() });
+// Helper function to start a test server with caller-supplied Args, for tests that need to
+// tweak a period, a limit, or auth_token away from the defaults. Most tests have no use for
+// the cleaner/reconciler (and shouldn't pay for them), so `run_background_tasks` only spawns
+// them for the handful of tests that actually exercise expiry or reconciliation.
+async fn start_test_server_with_args(
+    args: Args,
+    run_background_tasks: bool,
+) -> (SocketAddr, Arc<AppState>) {
+    let (cleaner_tx, cleaner_rx) = tokio::sync::mpsc::unbounded_channel();
+    let staging = StagingDir::init(&args.file_path).await.unwrap();
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let app_state = Arc::new(AppState {
+        args: args.clone(),
+        cleaner_tx,
+        staging,
+        view_locks: util::meta::new_view_locks(),
+        shutdown: shutdown.clone(),
+    });
 
     // Create the router using the application's function
     let app = create_app_router(app_state.clone());
@@ -37,6 +65,22 @@ async fn start_test_server(temp_dir: &TempDir) -> (SocketAddr, Arc<AppState>) {
         axum::serve(listener, app).await.unwrap();
     });
 
+    if run_background_tasks {
+        tokio::spawn(util::cleaner::cleaner_task(
+            args.file_path.clone(),
+            args.clean_period,
+            cleaner_rx,
+            args.cleaner_concurrency,
+            shutdown.clone(),
+        ));
+        tokio::spawn(util::cleaner::reconcile_task(
+            args.file_path,
+            args.cleaner_concurrency,
+            args.reconcile_period_secs,
+            shutdown,
+        ));
+    }
+
     // Give the server a moment to start
     time::sleep(Duration::from_millis(100)).await;
 
@@ -446,3 +490,482 @@ async fn test_binary_data_handling() {
     assert_eq!(retrieved_data.len(), binary_data.len());
     assert_eq!(&retrieved_data[..], &binary_data[..]);
 }
+
+#[tokio::test]
+async fn test_conditional_get_returns_not_modified() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, _) = start_test_server(&temp_dir).await;
+
+    let test_content = "Some cacheable content";
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body(test_content.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    // 1. A plain GET carries an ETag and a Cache-Control max-age.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res
+        .headers()
+        .get("etag")
+        .expect("response missing ETag")
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(res.headers().get("cache-control").is_some());
+
+    // 2. Replaying that ETag via If-None-Match gets a 304 with no body.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers().get("etag").unwrap().to_str().unwrap(), etag);
+    assert_eq!(res.text().await.unwrap(), "");
+}
+
+#[tokio::test]
+async fn test_gzip_negotiated_above_compression_threshold() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, app_state) = start_test_server(&temp_dir).await;
+
+    // Content well above compression_min_size (1024 bytes) so it's eligible for gzip.
+    let test_content = "x".repeat(app_state.args.compression_min_size * 2);
+
+    let raw_client = reqwest::Client::new();
+
+    let res = raw_client
+        .put(&format!("http://{addr}"))
+        .body(test_content.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    let res = raw_client
+        .get(&format!("http://{addr}/{hash}"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("content-encoding").unwrap(),
+        "gzip"
+    );
+
+    // The body is gzip-compressed on the wire; decoding it should round-trip the content.
+    let compressed = res.bytes().await.unwrap();
+    let mut decoder = async_compression::tokio::bufread::GzipDecoder::new(tokio::io::BufReader::new(
+        std::io::Cursor::new(compressed.to_vec()),
+    ));
+    let mut decoded = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut decoder, &mut decoded)
+        .await
+        .unwrap();
+    assert_eq!(decoded, test_content);
+}
+
+#[tokio::test]
+async fn test_oneshot_paste_burns_after_one_read() {
+    // Create a temporary directory for file storage. Burning a oneshot paste is handed off
+    // to the cleaner task rather than deleted inline, so it needs to actually be running.
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, _) = start_test_server_with_args(default_test_args(&temp_dir), true).await;
+
+    let test_content = "Read me exactly once";
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .header("X-Oneshot", "true")
+        .body(test_content.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    // 1. The first read succeeds and returns the content.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), test_content);
+
+    // Give the cleaner task a moment to process the deletion it was handed.
+    time::sleep(Duration::from_millis(200)).await;
+
+    // 2. The second read finds the paste gone.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_upload_requires_bearer_token_when_configured() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut args = default_test_args(&temp_dir);
+    args.auth_token = vec!["super-secret-token".to_string()];
+    let (addr, _) = start_test_server_with_args(args, false).await;
+
+    let client = reqwest::Client::new();
+
+    // 1. No Authorization header is rejected.
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body("unauthorized upload")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // 2. The wrong token is also rejected.
+    let res = client
+        .put(&format!("http://{addr}"))
+        .header("Authorization", "Bearer wrong-token")
+        .body("unauthorized upload")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // 3. The configured token is accepted.
+    let res = client
+        .put(&format!("http://{addr}"))
+        .header("Authorization", "Bearer super-secret-token")
+        .body("authorized upload")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_streamed_upload_at_exactly_the_limit_succeeds() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, app_state) = start_test_server(&temp_dir).await;
+
+    // Exercises the streamed body-read path (map_body_error/DefaultBodyLimit) right at the
+    // boundary, complementing test_file_size_limit's one-byte-over case.
+    let content_at_limit = "a".repeat(app_state.args.limit_size);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body(content_at_limit.clone())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), content_at_limit);
+}
+
+#[tokio::test]
+async fn test_head_matches_get_without_a_body() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, _) = start_test_server(&temp_dir).await;
+
+    let test_content = "Some content to HEAD";
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body(test_content.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    let get_res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    let head_res = client
+        .head(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(head_res.status(), get_res.status());
+    assert_eq!(
+        head_res.headers().get("etag"),
+        get_res.headers().get("etag")
+    );
+    assert_eq!(
+        head_res.headers().get("content-type"),
+        get_res.headers().get("content-type")
+    );
+    assert_eq!(head_res.bytes().await.unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_info_query_returns_metadata_without_content() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, _) = start_test_server(&temp_dir).await;
+
+    let test_content = "Some content to inspect";
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body(test_content.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    let res = client
+        .get(&format!("http://{addr}/{hash}?info"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let info: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(info["size"], test_content.len());
+    assert_eq!(info["is_redirect"], false);
+    assert_eq!(info["content_type"], "text/plain; charset=utf-8");
+
+    // ?info doesn't consume the paste: a normal GET afterwards still returns the content.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), test_content);
+}
+
+#[tokio::test]
+async fn test_expire_exceeding_server_max_is_rejected() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, app_state) = start_test_server(&temp_dir).await;
+
+    let res = reqwest::Client::new()
+        .put(&format!("http://{addr}"))
+        .header("X-Expire", (app_state.args.max_expire_secs + 1).to_string())
+        .body("too long a TTL")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let error_message = res.text().await.unwrap();
+    assert_eq!(error_message, "Requested TTL exceeds the server's maximum");
+
+    // The rejected upload shouldn't leave a stored paste behind (only the `.staging`
+    // subdirectory the server itself creates at startup).
+    let mut entries = tokio::fs::read_dir(temp_dir.path()).await.unwrap();
+    while let Some(entry) = entries.next_entry().await.unwrap() {
+        assert_eq!(entry.file_name(), std::ffi::OsStr::new(".staging"));
+    }
+}
+
+#[tokio::test]
+async fn test_expire_at_server_max_is_accepted() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, app_state) = start_test_server(&temp_dir).await;
+
+    let res = reqwest::Client::new()
+        .put(&format!("http://{addr}"))
+        .header("X-Expire", app_state.args.max_expire_secs.to_string())
+        .body("right at the limit")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_max_views_burns_after_exactly_n_reads() {
+    // Burning a max-views paste is handed off to the cleaner task rather than deleted
+    // inline, so it needs to actually be running; this also exercises the event-driven
+    // scheduler picking up the immediate deletion the moment the budget hits zero, rather
+    // than waiting for a poll tick.
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, _) = start_test_server_with_args(default_test_args(&temp_dir), true).await;
+
+    let test_content = "Read me exactly three times";
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .header("X-Max-Views", "3")
+        .body(test_content.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await.unwrap();
+    let hash = extract_hash(&body).expect("Failed to extract hash from response");
+
+    // The first two reads succeed and leave the paste in place.
+    for _ in 0..2 {
+        let res = client
+            .get(&format!("http://{addr}/{hash}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await.unwrap(), test_content);
+    }
+
+    // The third read is the last one the budget allows.
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), test_content);
+
+    // Give the cleaner task a moment to process the deletion it was handed.
+    time::sleep(Duration::from_millis(200)).await;
+
+    let res = client
+        .get(&format!("http://{addr}/{hash}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_reconcile_removes_orphaned_sidecars() {
+    // Reconciliation runs on a slow, fixed cadence, so use a short period rather than
+    // waiting out the real default.
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut args = default_test_args(&temp_dir);
+    args.reconcile_period_secs = 1;
+    let (_addr, _) = start_test_server_with_args(args, true).await;
+
+    // A `.gz`/`.meta` sidecar pair with no primary `.txt` file: left behind by a paste
+    // that's already been deleted through some other path.
+    let orphan_gz = temp_dir.path().join("orphan.txt.gz");
+    let orphan_meta = temp_dir.path().join("orphan.txt.meta");
+    tokio::fs::write(&orphan_gz, b"stale compressed data")
+        .await
+        .unwrap();
+    tokio::fs::write(&orphan_meta, b"{}").await.unwrap();
+
+    // Give the reconcile sweep time to run at least once.
+    time::sleep(Duration::from_millis(1500)).await;
+
+    assert!(!orphan_gz.exists());
+    assert!(!orphan_meta.exists());
+}
+
+#[tokio::test]
+async fn test_aborted_upload_leaves_no_staged_tmp_file() {
+    // Create a temporary directory for file storage
+    let temp_dir = tempfile::tempdir().unwrap();
+    let (addr, app_state) = start_test_server(&temp_dir).await;
+
+    // Streams past the size limit, so the upload errors out of `put_handler` partway
+    // through digesting the body instead of ever reaching `tmp.persist_to`.
+    let oversized_content = "a".repeat(app_state.args.limit_size + 1);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("http://{addr}"))
+        .body(oversized_content)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // `TmpFile`'s `Drop` cleans up the staging file as soon as the aborted `result` is
+    // dropped; give that spawned cleanup task a moment to run.
+    time::sleep(Duration::from_millis(200)).await;
+
+    let staging_dir = temp_dir.path().join(".staging");
+    let mut entries = tokio::fs::read_dir(&staging_dir).await.unwrap();
+    assert!(entries.next_entry().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_cleaner_task_drains_in_flight_deletions_on_shutdown() {
+    // Exercises `cleaner_task` directly rather than through HTTP: a deletion already handed
+    // off before shutdown is requested should still run to completion, not be abandoned.
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("K8mw.txt");
+    tokio::fs::write(&file_path, b"doomed content").await.unwrap();
+
+    let (cleaner_tx, cleaner_rx) = tokio::sync::mpsc::unbounded_channel();
+    let shutdown = tokio_util::sync::CancellationToken::new();
+
+    let handle = tokio::spawn(util::cleaner::cleaner_task(
+        temp_dir.path().to_string_lossy().into_owned(),
+        3600,
+        cleaner_rx,
+        10,
+        shutdown.clone(),
+    ));
+
+    cleaner_tx
+        .send(util::cleaner::ScheduledDeletion {
+            deadline: tokio::time::Instant::now(),
+            file_path: file_path.clone(),
+            scheduled_at: util::meta::unix_now().unwrap(),
+        })
+        .unwrap();
+
+    // Give the task a moment to pick the deletion off the channel and spawn it before the
+    // shutdown signal races in.
+    time::sleep(Duration::from_millis(50)).await;
+    shutdown.cancel();
+
+    handle.await.unwrap().unwrap();
+
+    assert!(!file_path.exists());
+}
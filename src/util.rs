@@ -1,11 +1,18 @@
 mod args;
-use std::sync::Arc;
 
 pub use args::Args;
 pub mod cleaner;
 pub mod handler;
+pub mod meta;
+pub mod tmpfile;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
-    pub args: Arc<Args>,
+    pub args: Args,
+    pub cleaner_tx: cleaner::CleanerHandle,
+    pub staging: tmpfile::StagingDir,
+    /// Serializes reads against each paste's `.meta` sidecar.
+    pub view_locks: meta::ViewLocks,
+    /// Cancelled once the server starts shutting down, so background tasks can wind down.
+    pub shutdown: tokio_util::sync::CancellationToken,
 }
@@ -23,4 +23,29 @@ pub struct Args {
     /// Syntax highlight theme (highlight.js)
     #[arg(short, long, default_value = "vs")]
     pub syntax_theme: String,
+
+    /// Minimum stored paste size (in bytes) eligible for gzip compression
+    #[arg(long, default_value_t = 1024)]
+    pub compression_min_size: usize,
+
+    /// Write a precompressed `.gz` sidecar alongside uploads eligible for compression
+    #[arg(long, default_value_t = true)]
+    pub write_compressed_sidecars: bool,
+
+    /// Bearer token(s) required to upload or delete pastes (repeatable, or comma-separated).
+    /// Leave unset to keep the server open to anonymous uploads.
+    #[arg(long, value_delimiter = ',')]
+    pub auth_token: Vec<String>,
+
+    /// Largest TTL (in seconds) a client may request via `X-Expire` on upload
+    #[arg(long, default_value_t = 7 * 24 * 3600)]
+    pub max_expire_secs: u64,
+
+    /// Maximum number of deletions the cleaner processes concurrently
+    #[arg(long, default_value_t = 10)]
+    pub cleaner_concurrency: usize,
+
+    /// How often the orphan-reconciliation sweep runs (in seconds)
+    #[arg(long, default_value_t = 6 * 3600)]
+    pub reconcile_period_secs: u64,
 }
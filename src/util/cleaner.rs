@@ -1,49 +1,298 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
 use anyhow::Result;
 use futures::StreamExt;
-use std::path::Path;
 use tokio::fs;
-use tracing::{debug, error, info};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use super::handler::gz_sidecar_path;
+use super::meta;
+
+/// How long the cleaner idles when nothing is scheduled, before re-checking.
+const IDLE_SLEEP_SECS: u64 = 3600;
+
+/// How long a `.tmp` staging file may sit unrenamed before `reconcile` treats it as abandoned.
+const ORPHAN_TMP_GRACE_SECS: u64 = 300;
+
+/// A paste's pending deletion, ordered by `deadline` for the min-heap (via `Reverse`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScheduledDeletion {
+    pub deadline: Instant,
+    pub file_path: PathBuf,
+    /// Unix time this entry was scheduled at, so `delete_paste` can tell a stale entry apart
+    /// from a file that's since been re-uploaded (and so already has its own entry queued).
+    pub scheduled_at: u64,
+}
+
+/// Lets uploaders tell the cleaner task about a freshly scheduled deadline.
+pub type CleanerHandle = mpsc::UnboundedSender<ScheduledDeletion>;
+
+/// Converts an absolute `expires_at` unix timestamp into a `tokio::time::Instant`, clamping
+/// to "now" if it's already due.
+pub fn deadline_from_unix(expires_at: u64, now_unix: u64) -> Instant {
+    match expires_at.checked_sub(now_unix) {
+        Some(remaining) if remaining > 0 => Instant::now() + Duration::from_secs(remaining),
+        _ => Instant::now(),
+    }
+}
+
+/// Event-driven replacement for a fixed-interval poll: sleeps until the earliest scheduled
+/// deadline, waking early whenever `rx` delivers a new one, and drains in-flight deletions
+/// before returning once `shutdown` fires.
+pub async fn cleaner_task(
+    storage_path: String,
+    period: u64,
+    mut rx: mpsc::UnboundedReceiver<ScheduledDeletion>,
+    concurrency: usize,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut heap: BinaryHeap<Reverse<ScheduledDeletion>> = BinaryHeap::new();
+    let mut in_flight: JoinSet<()> = JoinSet::new();
 
-const CLEAN_CHECK_PERIOD_SECS: u64 = 60; // 1 minute
+    if let Err(e) = seed_heap(&storage_path, period, &mut heap).await {
+        error!("Error seeding expiry heap: {:?}", e);
+    }
 
-pub async fn cleaner_task(storage_path: String, period: u64) -> Result<()> {
-    tokio::time::sleep(tokio::time::Duration::from_secs(CLEAN_CHECK_PERIOD_SECS)).await;
     loop {
-        debug!("Cleaning up expired files in {:?}", storage_path);
-        if let Err(e) = clean_up(&storage_path, period).await {
-            error!("Error cleaning up files: {:?}", e);
+        let next_wake = heap
+            .peek()
+            .map(|Reverse(entry)| entry.deadline)
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(IDLE_SLEEP_SECS));
+
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep_until(next_wake) => {
+                let now = Instant::now();
+                while let Some(Reverse(entry)) = heap.peek() {
+                    if entry.deadline > now {
+                        break;
+                    }
+                    let Reverse(entry) = heap.pop().unwrap();
+                    spawn_deletion(&mut in_flight, concurrency, entry.file_path, entry.scheduled_at).await;
+                }
+            }
+            entry = rx.recv() => match entry {
+                Some(entry) => heap.push(Reverse(entry)),
+                None => break,
+            },
+        }
+    }
+
+    while let Some(result) = in_flight.join_next().await {
+        if let Err(e) = result {
+            error!("Deletion task panicked: {:?}", e);
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(CLEAN_CHECK_PERIOD_SECS)).await;
     }
+
+    Ok(())
 }
 
-async fn clean_up(dir: &str, period: u64) -> Result<()> {
+/// Spawns a deletion onto `in_flight`, waiting for a free slot if `concurrency` is maxed out.
+async fn spawn_deletion(
+    in_flight: &mut JoinSet<()>,
+    concurrency: usize,
+    file_path: PathBuf,
+    scheduled_at: u64,
+) {
+    while in_flight.len() >= concurrency {
+        if let Some(Err(e)) = in_flight.join_next().await {
+            error!("Deletion task panicked: {:?}", e);
+        }
+    }
+    in_flight.spawn(async move { delete_paste(&file_path, scheduled_at).await });
+}
+
+/// Scans `dir` once at startup so files uploaded before this process started still get a
+/// deadline.
+async fn seed_heap(
+    dir: &str,
+    period: u64,
+    heap: &mut BinaryHeap<Reverse<ScheduledDeletion>>,
+) -> Result<()> {
     let path = Path::new(dir);
     let read_dir = fs::read_dir(path).await?;
+    let mut entries = tokio_stream::wrappers::ReadDirStream::new(read_dir);
+
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let file_path = entry.path();
+
+        // Sidecars (`.meta`, `.gz`) are reaped alongside their source file, not scheduled
+        // in their own right.
+        if file_path.extension().and_then(|e| e.to_str()) != Some("txt") {
+            continue;
+        }
+
+        let now_unix = meta::unix_now()?;
+        let expires_at = match meta::read_meta(&file_path).await.and_then(|m| m.expires_at) {
+            Some(at) => at,
+            None => {
+                let modified = entry.metadata().await?.modified()?;
+                modified.duration_since(UNIX_EPOCH)?.as_secs() + period
+            }
+        };
+
+        heap.push(Reverse(ScheduledDeletion {
+            deadline: deadline_from_unix(expires_at, now_unix),
+            file_path,
+            scheduled_at: now_unix,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Backs off if `file_path` was re-uploaded (the new content's mtime is later) after `scheduled_at`,
+/// since the newer upload already queued its own deletion; this covers re-uploads with no
+/// `.meta` sidecar at all, not just ones with a later `expires_at`. A views-exhausted paste
+/// always proceeds immediately regardless of mtime.
+async fn delete_paste(file_path: &Path, scheduled_at: u64) {
+    let exhausted = meta::read_meta(file_path)
+        .await
+        .is_some_and(|m| m.is_exhausted());
+    if !exhausted {
+        if let Ok(Some(mtime)) = mtime_unix(file_path).await {
+            if mtime > scheduled_at {
+                return;
+            }
+        }
+    }
 
-    let read_dir_stream = tokio_stream::wrappers::ReadDirStream::new(read_dir);
+    if fs::remove_file(file_path).await.is_ok() {
+        let _ = fs::remove_file(gz_sidecar_path(file_path)).await;
+        meta::remove_meta(file_path).await;
+        info!("Deleted file: {:?}", file_path);
+    }
+}
 
-    read_dir_stream
-        .for_each_concurrent(10, |entry| async move {
-            let result: Result<()> = async {
-                let entry = entry?;
-                let file_path = entry.path();
-                let metadata = fs::metadata(&file_path).await?;
-                let last_modified = metadata.modified()?.elapsed()?.as_secs();
+/// The file's last-modified time as a unix timestamp, or `None` if it's already gone.
+async fn mtime_unix(file_path: &Path) -> Result<Option<u64>> {
+    let metadata = match fs::metadata(file_path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let modified = metadata.modified()?;
+    Ok(Some(modified.duration_since(UNIX_EPOCH)?.as_secs()))
+}
 
-                if last_modified > period {
-                    fs::remove_file(&file_path).await?;
-                    info!("Deleted file: {:?}", file_path);
+/// Runs `reconcile` on a slow, fixed cadence in the background until `shutdown` fires.
+pub async fn reconcile_task(
+    storage_path: String,
+    concurrency: usize,
+    period_secs: u64,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            _ = tokio::time::sleep(Duration::from_secs(period_secs)) => {
+                if let Err(e) = reconcile(&storage_path, concurrency).await {
+                    error!("Error reconciling storage directory: {:?}", e);
                 }
-                anyhow::Ok(())
             }
-            .await;
+        }
+    }
+}
+
+/// Sweeps `dir` for orphaned `.meta`/`.gz` sidecars and abandoned `.tmp` uploads that the
+/// event-driven deletions never see.
+async fn reconcile(dir: &str, concurrency: usize) -> Result<()> {
+    let path = Path::new(dir);
+    let read_dir = fs::read_dir(path).await?;
+    let mut entries = tokio_stream::wrappers::ReadDirStream::new(read_dir);
+
+    let mut known = HashSet::new();
+    let mut sidecars = Vec::new();
+
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let file_path = entry.path();
+        let Some(name) = file_path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        if name.ends_with(".meta") || name.ends_with(".gz") {
+            sidecars.push(file_path);
+        } else if file_path.extension().and_then(|e| e.to_str()) == Some("txt") {
+            known.insert(file_path);
+        }
+    }
+
+    let mut in_flight: JoinSet<()> = JoinSet::new();
+    for sidecar in sidecars {
+        let orphaned = primary_for_sidecar(&sidecar).is_none_or(|primary| !known.contains(&primary));
+        if !orphaned {
+            continue;
+        }
+
+        while in_flight.len() >= concurrency {
+            in_flight.join_next().await;
+        }
+        in_flight.spawn(async move {
+            if fs::remove_file(&sidecar).await.is_ok() {
+                info!("Removed orphaned sidecar: {:?}", sidecar);
+            }
+        });
+    }
+    while in_flight.join_next().await.is_some() {}
+
+    reap_stale_tmp_files(&path.join(".staging"), concurrency).await
+}
+
+/// Removes `.tmp` staging files old enough to rule out an upload still in flight.
+async fn reap_stale_tmp_files(staging_dir: &Path, concurrency: usize) -> Result<()> {
+    let Ok(read_dir) = fs::read_dir(staging_dir).await else {
+        return Ok(());
+    };
+    let mut entries = tokio_stream::wrappers::ReadDirStream::new(read_dir);
+    let mut in_flight: JoinSet<()> = JoinSet::new();
+
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let file_path = entry.path();
 
-            if let Err(e) = result {
-                error!("Error processing file: {:?}", e);
+        if file_path.extension().and_then(|e| e.to_str()) != Some("tmp") {
+            continue;
+        }
+
+        let is_stale = entry
+            .metadata()
+            .await
+            .ok()
+            .and_then(|m| m.modified().ok())
+            .and_then(|m| m.elapsed().ok())
+            .is_some_and(|age| age.as_secs() >= ORPHAN_TMP_GRACE_SECS);
+
+        if !is_stale {
+            continue;
+        }
+
+        while in_flight.len() >= concurrency {
+            in_flight.join_next().await;
+        }
+        in_flight.spawn(async move {
+            if fs::remove_file(&file_path).await.is_ok() {
+                info!("Removed orphaned upload: {:?}", file_path);
             }
-        })
-        .await;
+        });
+    }
+    while in_flight.join_next().await.is_some() {}
 
     Ok(())
 }
+
+/// Recovers the `.txt` paste path a `.gz`/`.meta` sidecar belongs to.
+fn primary_for_sidecar(sidecar: &Path) -> Option<PathBuf> {
+    let os = sidecar.as_os_str().to_str()?;
+    os.strip_suffix(".gz")
+        .or_else(|| os.strip_suffix(".meta"))
+        .map(PathBuf::from)
+}
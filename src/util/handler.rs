@@ -1,21 +1,27 @@
-use std::{path::Path as FilePath, sync::Arc, time::UNIX_EPOCH};
+use std::{path::Path as FilePath, sync::Arc, time::SystemTime, time::UNIX_EPOCH};
 
+use async_compression::tokio::{bufread::GzipEncoder as BufReadGzipEncoder, write::GzipEncoder};
 use axum::{
-    body::{Body, Bytes},
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode, header, uri::Scheme},
+    Json,
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, HeaderName, StatusCode, header, uri::Scheme},
     response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::{TypedHeader, headers::Host};
+use futures::TryStreamExt;
 use indoc::formatdoc;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{self, File as TokioFile},
-    io::AsyncWriteExt,
+    io::{self, AsyncReadExt, AsyncWriteExt, BufReader},
 };
 use tokio_util::io::ReaderStream;
 use tracing::info;
 
 use super::AppState;
+use super::cleaner;
+use super::meta::{self, PasteMeta};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -24,10 +30,18 @@ pub enum AppError {
     NotFound,
     #[error("Permission denied")]
     Forbidden,
+    #[error("Missing or invalid bearer token")]
+    Unauthorized,
+    #[error("Payload too large")]
+    PayloadTooLarge,
+    #[error("Requested TTL exceeds the server's maximum")]
+    ExpireTooLong,
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("System time error: {0}")]
     SystemTimeError(#[from] std::time::SystemTimeError),
+    #[error("Metadata serialization error: {0}")]
+    MetaError(#[from] serde_json::Error),
 }
 
 impl IntoResponse for AppError {
@@ -35,14 +49,176 @@ impl IntoResponse for AppError {
         let (status, error_message) = match self {
             AppError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::Forbidden => (StatusCode::FORBIDDEN, self.to_string()),
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            AppError::ExpireTooLong => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::IoError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
             AppError::SystemTimeError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            AppError::MetaError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
         };
 
         (status, error_message).into_response()
     }
 }
 
+/// How long clients may cache a paste before revalidating.
+const CACHE_MAX_AGE_SECS: u64 = 3600;
+
+/// Strong validator for a stored file: the hash is already content-derived, and the
+/// timestamp changes whenever the file is overwritten, so the pair uniquely identifies
+/// a specific version of the paste.
+fn compute_etag(hash: &str, timestamp: &str) -> String {
+    format!("\"{}-{}\"", hash, timestamp)
+}
+
+/// Returns the `.gz` sidecar path that sits next to a stored file.
+pub(crate) fn gz_sidecar_path(file_path: &std::path::Path) -> std::path::PathBuf {
+    let mut os = file_path.as_os_str().to_owned();
+    os.push(".gz");
+    std::path::PathBuf::from(os)
+}
+
+/// Outcome of `claim_view`.
+enum ViewClaim {
+    /// No views budget configured.
+    Unlimited,
+    /// A view was claimed.
+    Claimed,
+    /// A concurrent read already claimed the last view.
+    AlreadyExhausted,
+}
+
+/// Claims a read against `file_path`'s views-remaining budget before any content is read off
+/// disk, so two requests racing a oneshot/max-views paste can't both serve the terminal view.
+async fn claim_view(
+    file_path: &std::path::Path,
+    view_locks: &meta::ViewLocks,
+    cleaner_tx: &cleaner::CleanerHandle,
+) -> ViewClaim {
+    let Some(meta) = meta::read_meta(file_path).await else {
+        return ViewClaim::Unlimited;
+    };
+    if meta.views_remaining.is_none() {
+        return ViewClaim::Unlimited;
+    }
+
+    let lock = view_locks
+        .entry(file_path.to_path_buf())
+        .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+        .clone();
+
+    let claim = {
+        let _guard = lock.lock().await;
+
+        match meta::read_meta(file_path).await.and_then(|m| m.views_remaining.map(|r| (m, r))) {
+            None | Some((_, 0)) => ViewClaim::AlreadyExhausted,
+            Some((meta, 1)) => {
+                let updated = PasteMeta {
+                    views_remaining: Some(0),
+                    ..meta
+                };
+                let _ = meta::write_meta(file_path, &updated).await;
+                let _ = cleaner_tx.send(cleaner::ScheduledDeletion {
+                    deadline: tokio::time::Instant::now(),
+                    file_path: file_path.to_path_buf(),
+                    scheduled_at: meta::unix_now().unwrap_or(0),
+                });
+                ViewClaim::Claimed
+            }
+            Some((meta, remaining)) => {
+                let updated = PasteMeta {
+                    views_remaining: Some(remaining - 1),
+                    ..meta
+                };
+                let _ = meta::write_meta(file_path, &updated).await;
+                ViewClaim::Claimed
+            }
+        }
+    };
+
+    // Drop our own clone before pruning so the strong count reflects only the map's
+    // reference (plus any other reader already queued behind the same key).
+    drop(lock);
+    view_locks.remove_if(file_path, |_, lock| Arc::strong_count(lock) <= 1);
+    claim
+}
+
+/// Requires a valid `Authorization: Bearer <token>` header when `tokens` is non-empty.
+fn require_auth(header_map: &HeaderMap, tokens: &[String]) -> Result<(), AppError> {
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    let provided = header_map
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    let Some(provided) = provided else {
+        return Err(AppError::Unauthorized);
+    };
+
+    let authorized = tokens
+        .iter()
+        .any(|token| constant_time_eq::constant_time_eq(token.as_bytes(), provided.as_bytes()));
+
+    if authorized {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized)
+    }
+}
+
+fn accepts_gzip(header_map: &HeaderMap) -> bool {
+    header_map
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|enc| enc.trim().starts_with("gzip")))
+}
+
+/// Opens the precompressed sidecar for `file_path`, but only if it's at least as fresh
+/// as the source file (otherwise it was left over from a previous upload with this hash).
+async fn fresh_gz_sidecar(file_path: &std::path::Path, source_modified: SystemTime) -> Option<TokioFile> {
+    let file = TokioFile::open(gz_sidecar_path(file_path)).await.ok()?;
+    let gz_modified = file.metadata().await.ok()?.modified().ok()?;
+    (gz_modified >= source_modified).then_some(file)
+}
+
+async fn gzip_bytes(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzipEncoder::new(Vec::new());
+    encoder.write_all(data).await?;
+    encoder.shutdown().await?;
+    Ok(encoder.into_inner())
+}
+
+/// Translates a body-read error into the matching `AppError`, preserving the 413 that
+/// `DefaultBodyLimit` would otherwise produce for an oversized streamed upload.
+fn map_body_error(err: axum::Error) -> AppError {
+    match err.into_inner().downcast::<http_body_util::LengthLimitError>() {
+        Ok(_) => AppError::PayloadTooLarge,
+        Err(other) => AppError::IoError(io::Error::other(other)),
+    }
+}
+
+/// Streams `file_path` through a gzip encoder into its `.gz` sidecar without buffering
+/// the whole file in memory.
+async fn write_gz_sidecar(file_path: &std::path::Path) -> Result<(), std::io::Error> {
+    let src = BufReader::new(TokioFile::open(file_path).await?);
+    let mut encoder = BufReadGzipEncoder::new(src);
+    let mut dst = TokioFile::create(gz_sidecar_path(file_path)).await?;
+    io::copy(&mut encoder, &mut dst).await?;
+    Ok(())
+}
+
+/// Headers shared by every cacheable, potentially-compressed response.
+fn base_headers(etag: String, cache_control: String) -> Vec<(HeaderName, String)> {
+    vec![
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, cache_control),
+        (header::VARY, "Accept-Encoding".to_string()),
+    ]
+}
+
 fn parse_filehash(file_hash: &str) -> (String, Option<String>) {
     let file_hash = std::path::Path::new(file_hash);
     let file_name = format!(
@@ -69,64 +245,319 @@ async fn file_to_timestamp(file: &TokioFile) -> Result<String, AppError> {
         .to_string())
 }
 
+/// Everything the content route, the `HEAD` route and the `?info` route need to know
+/// about a stored paste, resolved once so each caller doesn't re-walk the filesystem.
+struct ResolvedPaste {
+    file_path: std::path::PathBuf,
+    file_ext: Option<String>,
+    modified: SystemTime,
+    size: u64,
+    etag: String,
+    meta: PasteMeta,
+}
+
+/// Looks up `file_hash` on disk, applying expiry the same way `get_handler` always has: a
+/// paste past its `expires_at`, or already exhausted by `claim_view`, is treated as already
+/// gone, and is cleaned up on the way.
+async fn resolve_paste(file_hash: &str, state: &AppState) -> Result<ResolvedPaste, AppError> {
+    let (file_name, file_ext) = parse_filehash(file_hash);
+    let hash = FilePath::new(file_hash)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string();
+
+    let file_path = FilePath::new(&state.args.file_path).join(file_name);
+
+    if !file_path.exists() {
+        return Err(AppError::NotFound);
+    }
+
+    let meta = meta::read_meta(&file_path).await.unwrap_or_default();
+    if meta.is_expired(meta::unix_now()?) || meta.is_exhausted() {
+        let _ = fs::remove_file(&file_path).await;
+        let _ = fs::remove_file(gz_sidecar_path(&file_path)).await;
+        meta::remove_meta(&file_path).await;
+        return Err(AppError::NotFound);
+    }
+
+    let metadata = fs::metadata(&file_path).await?;
+    let modified = metadata.modified()?;
+
+    Ok(ResolvedPaste {
+        file_path,
+        file_ext,
+        modified,
+        size: metadata.len(),
+        etag: compute_etag(&hash, &modified.duration_since(UNIX_EPOCH)?.as_secs().to_string()),
+        meta,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct InfoQuery {
+    info: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PasteInfo {
+    size: u64,
+    modified: u64,
+    content_type: String,
+    is_redirect: bool,
+    expires_at: Option<u64>,
+}
+
+/// How much of a paste's content `classify_content` looks at, instead of reading the whole
+/// file the way `get_handler` ultimately does.
+const CONTENT_SNIFF_BYTES: u64 = 8192;
+
+/// Reads up to `max_len` bytes from the start of `file_path`.
+async fn read_prefix(file_path: &std::path::Path, max_len: u64) -> Option<Vec<u8>> {
+    let file = TokioFile::open(file_path).await.ok()?;
+    let mut buf = Vec::new();
+    file.take(max_len).read_to_end(&mut buf).await.ok()?;
+    Some(buf)
+}
+
+/// Approximates `get_handler`'s content-type decision from a bounded prefix and the
+/// size/extension already resolved in `ResolvedPaste`, rather than buffering the whole file
+/// just to answer a `HEAD` or `?info` request.
+async fn classify_content(
+    file_path: &std::path::Path,
+    file_ext: &Option<String>,
+    size: u64,
+) -> (bool, String, Option<String>) {
+    let prefix = read_prefix(file_path, size.min(CONTENT_SNIFF_BYTES)).await;
+    let text = prefix.as_deref().and_then(|p| std::str::from_utf8(p).ok());
+
+    let location = text
+        .filter(|_| size <= CONTENT_SNIFF_BYTES)
+        .filter(|c| c.starts_with("http") && !c.contains([' ', '\n']));
+
+    let content_type = if location.is_some() {
+        "text/uri-list".to_string()
+    } else if text.is_none() {
+        mime_guess::from_ext(&file_ext.clone().unwrap_or_default())
+            .first_or_octet_stream()
+            .to_string()
+    } else if file_ext.as_ref().is_none_or(|ext| ext == "txt") {
+        "text/plain; charset=utf-8".to_string()
+    } else {
+        "text/html; charset=utf-8".to_string()
+    };
+
+    (location.is_some(), content_type, location.map(str::to_string))
+}
+
+/// `GET /{*hash}?info` — a cheap stat-like view of a paste that doesn't download it.
+async fn info_response(paste: ResolvedPaste) -> Result<Response, AppError> {
+    let (is_redirect, content_type, _location) =
+        classify_content(&paste.file_path, &paste.file_ext, paste.size).await;
+
+    let info = PasteInfo {
+        size: paste.size,
+        modified: paste.modified.duration_since(UNIX_EPOCH)?.as_secs(),
+        content_type,
+        is_redirect,
+        expires_at: paste.meta.expires_at,
+    };
+
+    Ok(Json(info).into_response())
+}
+
+/// `HEAD /{*hash}` — same status and headers `GET` would return, without a body, except
+/// that `Content-Length` is not the length of the `GET` body and the content type comes
+/// from `classify_content`'s bounded sniff rather than the whole file. Builds
+/// them straight from `resolve_paste` instead of delegating to `get_handler`, so probing a
+/// burn-after-read or max-views paste doesn't call `claim_view` and burn it, and so it
+/// skips the gzip-encode work a real `GET` does.
+pub async fn head_handler(
+    Path(file_hash): Path<String>,
+    header_map: HeaderMap,
+    Query(info_query): Query<InfoQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let paste = resolve_paste(&file_hash, &state).await?;
+
+    if info_query.info.is_some() {
+        let response = info_response(paste).await?;
+        let (parts, _body) = response.into_parts();
+        return Ok(Response::from_parts(parts, Body::empty()));
+    }
+
+    let cache_control = format!("public, max-age={}", CACHE_MAX_AGE_SECS);
+
+    if header_map
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == paste.etag)
+    {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, paste.etag.clone()),
+                (header::CACHE_CONTROL, cache_control),
+            ],
+        )
+            .into_response());
+    }
+
+    let want_gzip =
+        accepts_gzip(&header_map) && paste.size as usize >= state.args.compression_min_size;
+    let (is_redirect, content_type, location) =
+        classify_content(&paste.file_path, &paste.file_ext, paste.size).await;
+
+    if is_redirect {
+        return Ok((
+            StatusCode::TEMPORARY_REDIRECT,
+            [(header::LOCATION, location.unwrap_or_default())],
+        )
+            .into_response());
+    }
+
+    let mut headers = base_headers(paste.etag, cache_control);
+    headers.push((header::CONTENT_TYPE, content_type));
+    if want_gzip {
+        headers.push((header::CONTENT_ENCODING, "gzip".to_string()));
+    }
+
+    Ok((StatusCode::OK, headers, Body::empty()).into_response())
+}
+
 pub async fn get_handler(
     Path(file_hash): Path<String>,
+    header_map: HeaderMap,
+    Query(info_query): Query<InfoQuery>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Response, AppError> {
-    let (file_name, file_ext) = parse_filehash(file_hash.as_str());
+    let paste = resolve_paste(&file_hash, &state).await?;
 
-    let dir = &state.args.file_path;
-    let file_path = FilePath::new(dir).join(file_name);
+    if info_query.info.is_some() {
+        return info_response(paste).await;
+    }
 
-    if file_path.exists() {
-        match fs::read_to_string(&file_path).await {
-            Ok(content) =>
-            // 302 redirect if the content is a valid URL
-            {
-                if content.starts_with("http") && !content.contains([' ', '\n']) {
-                    Ok(Redirect::temporary(&content).into_response())
-                } else if file_ext.as_ref().is_none_or(|ext| ext == "txt") {
-                    Ok(content.into_response())
-                } else {
-                    Ok((
-                        StatusCode::OK,
-                        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
-                        formatdoc! {
-                            r#"
-                            <head>
-                                <link rel="stylesheet" href="https://cdn.jsdelivr.net/gh/highlightjs/cdn-release@11.9.0/build/styles/{}.css">
-                                <script src="https://cdn.jsdelivr.net/gh/highlightjs/cdn-release@11.9.0/build/highlight.min.js"></script>
-                                <script>hljs.highlightAll();</script>
-                            </head>
-                            <body>
-                            <pre><code class="{}">{}</code></pre>
-                            </body>
-                            "#,
-                            state.args.syntax_theme,
-                            file_ext.unwrap_or_default(),
-                            content
-                        }
-                    ).into_response())
+    let ResolvedPaste {
+        file_path,
+        file_ext,
+        modified,
+        size,
+        etag,
+        meta: _,
+    } = paste;
+
+    let cache_control = format!("public, max-age={}", CACHE_MAX_AGE_SECS);
+
+    if header_map
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == etag)
+    {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, cache_control),
+            ],
+        )
+            .into_response());
+    }
+
+    // Claimed before any content is read off disk: a oneshot/max-views paste's budget is
+    // decremented (and the sidecar updated) right here, so a request racing this one past
+    // `resolve_paste` can't also go on to read and serve the same terminal view.
+    if matches!(
+        claim_view(&file_path, &state.view_locks, &state.cleaner_tx).await,
+        ViewClaim::AlreadyExhausted
+    ) {
+        return Err(AppError::NotFound);
+    }
+
+    let want_gzip = accepts_gzip(&header_map) && size as usize >= state.args.compression_min_size;
+
+    match fs::read_to_string(&file_path).await {
+        Ok(content) =>
+        // 307 redirect if the content is a valid URL
+        {
+            if content.starts_with("http") && !content.contains([' ', '\n']) {
+                Ok(Redirect::temporary(&content).into_response())
+            } else if file_ext.as_ref().is_none_or(|ext| ext == "txt") {
+                let mut headers = base_headers(etag, cache_control);
+                headers.push((
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ));
+
+                if want_gzip {
+                    headers.push((header::CONTENT_ENCODING, "gzip".to_string()));
+                    if let Some(gz_file) = fresh_gz_sidecar(&file_path, modified).await {
+                        let body = Body::from_stream(ReaderStream::new(gz_file));
+                        return Ok((StatusCode::OK, headers, body).into_response());
+                    }
+                    let compressed = gzip_bytes(content.as_bytes()).await?;
+                    return Ok((StatusCode::OK, headers, compressed).into_response());
                 }
-            }
-            _ => match TokioFile::open(&file_path).await {
-                Ok(file) => {
-                    let stream = ReaderStream::new(file);
-                    let body = Body::from_stream(stream);
-                    let content_type = mime_guess::from_ext(&file_ext.unwrap_or_default())
-                        .first_or_octet_stream()
-                        .to_string();
-
-                    Ok(
-                        (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], body)
-                            .into_response(),
-                    )
+
+                Ok((StatusCode::OK, headers, content).into_response())
+            } else {
+                let html = formatdoc! {
+                    r#"
+                    <head>
+                        <link rel="stylesheet" href="https://cdn.jsdelivr.net/gh/highlightjs/cdn-release@11.9.0/build/styles/{}.css">
+                        <script src="https://cdn.jsdelivr.net/gh/highlightjs/cdn-release@11.9.0/build/highlight.min.js"></script>
+                        <script>hljs.highlightAll();</script>
+                    </head>
+                    <body>
+                    <pre><code class="{}">{}</code></pre>
+                    </body>
+                    "#,
+                    state.args.syntax_theme,
+                    file_ext.unwrap_or_default(),
+                    content
+                };
+
+                let mut headers = base_headers(etag, cache_control);
+                headers.push((
+                    header::CONTENT_TYPE,
+                    "text/html; charset=utf-8".to_string(),
+                ));
+
+                if want_gzip {
+                    headers.push((header::CONTENT_ENCODING, "gzip".to_string()));
+                    let compressed = gzip_bytes(html.as_bytes()).await?;
+                    return Ok((StatusCode::OK, headers, compressed).into_response());
                 }
-                Err(e) => Err(AppError::IoError(e)),
-            },
+
+                Ok((StatusCode::OK, headers, html).into_response())
+            }
         }
-    } else {
-        Err(AppError::NotFound)
+        _ => match TokioFile::open(&file_path).await {
+            Ok(file) => {
+                let content_type = mime_guess::from_ext(&file_ext.unwrap_or_default())
+                    .first_or_octet_stream()
+                    .to_string();
+
+                let mut headers = base_headers(etag, cache_control);
+                headers.push((header::CONTENT_TYPE, content_type));
+
+                if want_gzip {
+                    headers.push((header::CONTENT_ENCODING, "gzip".to_string()));
+
+                    if let Some(gz_file) = fresh_gz_sidecar(&file_path, modified).await {
+                        let body = Body::from_stream(ReaderStream::new(gz_file));
+                        return Ok((StatusCode::OK, headers, body).into_response());
+                    }
+
+                    let gz = BufReadGzipEncoder::new(BufReader::new(file));
+                    let body = Body::from_stream(ReaderStream::new(gz));
+                    return Ok((StatusCode::OK, headers, body).into_response());
+                }
+
+                let body = Body::from_stream(ReaderStream::new(file));
+                Ok((StatusCode::OK, headers, body).into_response())
+            }
+            Err(e) => Err(AppError::IoError(e)),
+        },
     }
 }
 
@@ -134,21 +565,105 @@ pub async fn put_handler(
     TypedHeader(host): TypedHeader<Host>,
     header_map: HeaderMap,
     State(state): State<Arc<AppState>>,
-    bytes: Bytes,
+    request: Request,
 ) -> Result<String, AppError> {
+    require_auth(&header_map, &state.args.auth_token)?;
+
     const HASHER: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
 
     use base64::prelude::*;
 
-    let hash = &BASE64_URL_SAFE.encode(HASHER.checksum(&bytes).to_be_bytes())[0..4];
+    let dir = FilePath::new(&state.args.file_path);
+    // Stage the upload in its own guarded temp file first: the hash that names the final
+    // file is only known once the whole stream has been digested, and an aborted or errored
+    // upload is cleaned up automatically when `tmp` drops instead of leaving a stray file.
+    let tmp = state.staging.new_tmp_file();
+    let mut tmp_file = TokioFile::create(tmp.path()).await?;
+
+    let result: Result<(u32, u64), AppError> = async {
+        let mut digest = HASHER.digest();
+        let mut size: u64 = 0;
+        let mut stream = axum::RequestExt::with_limited_body(request)
+            .into_body()
+            .into_data_stream();
+
+        while let Some(chunk) = stream.try_next().await.map_err(map_body_error)? {
+            digest.update(&chunk);
+            size += chunk.len() as u64;
+            tmp_file.write_all(&chunk).await?;
+        }
+        tmp_file.flush().await?;
+
+        Ok((digest.finalize(), size))
+    }
+    .await;
+
+    // `tmp`'s `Drop` cleans up the staging file automatically on error.
+    let (crc, size) = result?;
+
+    let hash = &BASE64_URL_SAFE.encode(crc.to_be_bytes())[0..4];
 
     let file_name = format!("{}.txt", hash);
-    let file_path = FilePath::new(&state.args.file_path).join(file_name);
-    let mut file = TokioFile::create(&file_path).await?;
+    let file_path = dir.join(file_name);
+    tmp.persist_to(&file_path).await?;
 
-    file.write_all(&bytes).await?;
+    let file = TokioFile::open(&file_path).await?;
+
+    info!("File saved: hash: {} size: {} bytes", hash, size);
 
-    info!("File saved: hash: {} size: {} bytes", hash, bytes.len());
+    if state.args.write_compressed_sidecars && size as usize >= state.args.compression_min_size {
+        write_gz_sidecar(&file_path).await?;
+    }
+
+    let ttl = header_map
+        .get("X-Expire")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if ttl.is_some_and(|ttl| ttl > state.args.max_expire_secs) {
+        let _ = fs::remove_file(&file_path).await;
+        let _ = fs::remove_file(gz_sidecar_path(&file_path)).await;
+        return Err(AppError::ExpireTooLong);
+    }
+    let expires_at = ttl
+        .map(|ttl| meta::unix_now().map(|now| now + ttl))
+        .transpose()?;
+    // `X-Max-Views: N` burns the paste after N reads; `X-Oneshot: true` is shorthand for
+    // `X-Max-Views: 1`, the classic burn-after-reading case.
+    let views_remaining = header_map
+        .get("X-Max-Views")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| {
+            header_map
+                .get("X-Oneshot")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("true"))
+                .then_some(1)
+        });
+
+    let paste_meta = PasteMeta {
+        expires_at,
+        views_remaining,
+    };
+    // Always reset the sidecar, even to "empty": the hash may belong to a prior upload whose
+    // `.meta` (e.g. an exhausted oneshot) would otherwise still apply to this new content.
+    if paste_meta.is_empty() {
+        meta::remove_meta(&file_path).await;
+    } else {
+        meta::write_meta(&file_path, &paste_meta).await?;
+    }
+
+    // Every paste gets a deadline, even without an explicit `X-Expire`: it just falls back
+    // to the server's global `clean_period`, same as the cleaner's startup scan does.
+    let now_unix = meta::unix_now()?;
+    let deadline_unix = expires_at.unwrap_or(now_unix + state.args.clean_period);
+    let _ = state.cleaner_tx.send(cleaner::ScheduledDeletion {
+        deadline: cleaner::deadline_from_unix(deadline_unix, now_unix),
+        file_path: file_path.clone(),
+        scheduled_at: now_unix,
+    });
+
+    let expires = deadline_unix.to_string();
 
     let protocal_str = header_map
         .get("X-Forwarded-Proto")
@@ -162,20 +677,25 @@ pub async fn put_handler(
         short: {hash}
         size: {size} bytes
         secret: {timestamp}
+        expires: {expires}
         ",
         protocal = protocal_str,
-        size = bytes.len(),
+        size = size,
         hash = hash,
         host = host,
-        timestamp = timestamp
+        timestamp = timestamp,
+        expires = expires
     })
 }
 
 pub async fn delete_handler(
     Path(file_hash): Path<String>,
+    header_map: HeaderMap,
     State(state): State<Arc<AppState>>,
     secret: String,
 ) -> Result<String, AppError> {
+    require_auth(&header_map, &state.args.auth_token)?;
+
     let (file_name, _) = parse_filehash(file_hash.as_str());
 
     let dir = &state.args.file_path;
@@ -187,6 +707,8 @@ pub async fn delete_handler(
 
     if file_path.exists() {
         if secret == timestamp {
+            let _ = fs::remove_file(gz_sidecar_path(&file_path)).await;
+            meta::remove_meta(&file_path).await;
             fs::remove_file(file_path).await?;
             Ok(format!("File {} deleted successfully", file_hash))
         } else {
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use super::handler::AppError;
+
+/// Per-path locks serializing read-modify-write access to a paste's `.meta` sidecar.
+pub type ViewLocks = Arc<DashMap<PathBuf, Arc<Mutex<()>>>>;
+
+pub fn new_view_locks() -> ViewLocks {
+    Arc::new(DashMap::new())
+}
+
+/// Per-paste metadata persisted alongside the stored file as a `<hash>.meta` sidecar.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PasteMeta {
+    /// Unix timestamp (seconds) after which the paste is considered expired.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Reads left before the paste is burned. `None` means unlimited; `Some(1)` is classic
+    /// burn-after-reading; `Some(0)` means exhausted, pending physical deletion.
+    #[serde(default)]
+    pub views_remaining: Option<u64>,
+}
+
+impl PasteMeta {
+    pub fn is_empty(&self) -> bool {
+        self.expires_at.is_none() && self.views_remaining.is_none()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+
+    /// True once a burn-after-read/max-views paste's budget has hit zero.
+    pub fn is_exhausted(&self) -> bool {
+        self.views_remaining == Some(0)
+    }
+}
+
+/// Returns the `.meta` sidecar path that sits next to a stored file.
+pub fn meta_path(file_path: &Path) -> PathBuf {
+    let mut os = file_path.as_os_str().to_owned();
+    os.push(".meta");
+    PathBuf::from(os)
+}
+
+pub async fn read_meta(file_path: &Path) -> Option<PasteMeta> {
+    let raw = fs::read(meta_path(file_path)).await.ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+pub async fn write_meta(file_path: &Path, meta: &PasteMeta) -> Result<(), AppError> {
+    let raw = serde_json::to_vec(meta)?;
+    fs::write(meta_path(file_path), raw).await?;
+    Ok(())
+}
+
+pub async fn remove_meta(file_path: &Path) {
+    let _ = fs::remove_file(meta_path(file_path)).await;
+}
+
+pub fn unix_now() -> Result<u64, std::time::SystemTimeError> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The staging subdirectory uploads land in before they're renamed into the storage
+/// directory proper.
+#[derive(Debug, Clone)]
+pub struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    /// Creates the staging directory under `storage_dir`, clearing out anything left over
+    /// from a previous run.
+    pub async fn init(storage_dir: &str) -> std::io::Result<Self> {
+        let path = Path::new(storage_dir).join(".staging");
+        let _ = fs::remove_dir_all(&path).await;
+        fs::create_dir_all(&path).await?;
+        Ok(Self { path })
+    }
+
+    /// Removes the staging directory entirely.
+    pub async fn cleanup(&self) {
+        if let Err(e) = fs::remove_dir_all(&self.path).await {
+            warn!("Failed to remove staging directory {:?}: {:?}", self.path, e);
+        }
+    }
+
+    /// Reserves a uniquely named temp file inside the staging directory for one upload.
+    pub fn new_tmp_file(&self) -> TmpFile {
+        TmpFile {
+            path: self.path.join(format!("{}.tmp", Uuid::new_v4())),
+            disarmed: false,
+        }
+    }
+}
+
+/// A staging temp file that deletes itself on drop unless persisted.
+#[derive(Debug)]
+pub struct TmpFile {
+    path: PathBuf,
+    disarmed: bool,
+}
+
+impl TmpFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the temp file into its final location, disarming the drop cleanup.
+    pub async fn persist_to(mut self, dest: &Path) -> std::io::Result<()> {
+        fs::rename(&self.path, dest).await?;
+        self.disarmed = true;
+        Ok(())
+    }
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if self.disarmed {
+            return;
+        }
+        let path = self.path.clone();
+        tokio::spawn(async move {
+            let _ = fs::remove_file(&path).await;
+        });
+    }
+}